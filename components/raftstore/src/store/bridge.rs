@@ -0,0 +1,496 @@
+// Copyright 2024 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! A Raft message bridge between heterogeneous engines.
+//!
+//! This promotes the test-only `ForwardFilter` hack into a real subsystem. Given
+//! a router handle per side it forwards `RaftMessage`s destined for a configured
+//! store id across a v1<->v2 boundary, translating between the two conventions
+//! (region epoch, `ExtraMessageType::MsgGcPeer{Request,Response}` and the
+//! tombstone / `is_tombstone` semantics) so that an external engine running
+//! `enable_v2_compatible_learner` can be driven by a live leader rather than a
+//! test harness. Conf-change removals propagate end to end, so the leader
+//! eventually clears `removed_records` and `merged_records`.
+//!
+//! The bridge carries no transport of its own: each side is supplied as a
+//! [`RaftMessageSink`], so an operator wires the v1 and v2 `RaftRouter`s (or any
+//! equivalent send handle) as the two sinks and feeds observed messages to
+//! [`RaftMessageBridge::on_v1_message`] / [`on_v2_message`](RaftMessageBridge::on_v2_message).
+//! The same `ForwardFilter` the tests use is just one such wiring.
+
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Arc, Mutex,
+};
+
+use kvproto::{
+    metapb::PeerRole,
+    raft_serverpb::{ExtraMessageType, RaftMessage},
+};
+
+use crate::store::read_index_forward::{self, ReadIndexLeader};
+
+/// Where a forwarded message is handed off. Both a v1 and a v2 store router
+/// implement this; the bridge is generic over them so it carries no dependency
+/// on any particular transport.
+pub trait RaftMessageSink: Send + Sync + 'static {
+    fn send(&self, msg: RaftMessage);
+}
+
+impl<F> RaftMessageSink for F
+where
+    F: Fn(RaftMessage) + Send + Sync + 'static,
+{
+    fn send(&self, msg: RaftMessage) {
+        (self)(msg)
+    }
+}
+
+/// Counters surfaced for observability.
+#[derive(Default)]
+pub struct BridgeMetrics {
+    forwarded: AtomicU64,
+    dropped: AtomicU64,
+    gc_peer: AtomicU64,
+}
+
+impl BridgeMetrics {
+    pub fn forwarded(&self) -> u64 {
+        self.forwarded.load(Ordering::Relaxed)
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// GC-peer handshake messages forwarded across the boundary. These are the
+    /// ones that must reach the other side for conf-change removals to converge.
+    pub fn gc_peer(&self) -> u64 {
+        self.gc_peer.load(Ordering::Relaxed)
+    }
+}
+
+/// Forwards Raft traffic between a v1 and a v2 cluster.
+///
+/// `learner_store` is the store id the external (v1-side) learner lives on, and
+/// `leader_store` is the store id the v2 leader lives on. Messages observed on
+/// one side that are addressed to the other side's store are translated and
+/// relayed; everything else is left untouched.
+pub struct RaftMessageBridge {
+    v1_sink: Arc<dyn RaftMessageSink>,
+    v2_sink: Arc<dyn RaftMessageSink>,
+    learner_store: u64,
+    leader_store: u64,
+    running: AtomicBool,
+    metrics: Arc<BridgeMetrics>,
+    /// When the v2 leader runs inside this process (the control-plane embedding
+    /// the request targets), the bridge answers a forwarded ReadIndex request
+    /// itself through [`read_index_forward::dispatch_extra_message`]. Left unset,
+    /// the request is relayed verbatim for a remote leader fsm to answer.
+    read_index_leader: Mutex<Option<Arc<dyn ReadIndexLeader>>>,
+}
+
+impl RaftMessageBridge {
+    pub fn new(
+        v1_sink: Arc<dyn RaftMessageSink>,
+        v2_sink: Arc<dyn RaftMessageSink>,
+        learner_store: u64,
+        leader_store: u64,
+    ) -> Arc<RaftMessageBridge> {
+        Arc::new(RaftMessageBridge {
+            v1_sink,
+            v2_sink,
+            learner_store,
+            leader_store,
+            running: AtomicBool::new(false),
+            metrics: Arc::new(BridgeMetrics::default()),
+            read_index_leader: Mutex::new(None),
+        })
+    }
+
+    /// Registers an in-process v2 leader so the bridge answers forwarded
+    /// ReadIndex requests itself instead of relaying them to a remote fsm.
+    pub fn set_read_index_leader(&self, leader: Arc<dyn ReadIndexLeader>) {
+        *self.read_index_leader.lock().unwrap() = Some(leader);
+    }
+
+    pub fn start(&self) {
+        self.running.store(true, Ordering::SeqCst);
+    }
+
+    pub fn stop(&self) {
+        self.running.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    pub fn metrics(&self) -> &Arc<BridgeMetrics> {
+        &self.metrics
+    }
+
+    /// The filtering predicate: whether a message seen anywhere should be
+    /// relayed toward `store_id`.
+    pub fn should_forward(msg: &RaftMessage, store_id: u64) -> bool {
+        msg.get_to_peer().get_store_id() == store_id
+    }
+
+    /// Handle a message observed on the v1 side. Messages addressed to the v2
+    /// leader store are translated into v2 conventions and relayed.
+    pub fn on_v1_message(&self, msg: RaftMessage) {
+        if !Self::should_forward(&msg, self.leader_store) {
+            return;
+        }
+        if !self.is_running() {
+            self.metrics.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        self.metrics.forwarded.fetch_add(1, Ordering::Relaxed);
+        if is_gc_peer_extra(msg.get_extra_msg().get_type()) {
+            self.metrics.gc_peer.fetch_add(1, Ordering::Relaxed);
+        }
+        // A ReadIndex request addressed to an in-process leader is answered here,
+        // with the reply routed back toward the learner; with no leader
+        // registered it is relayed to the v2 side like any other message.
+        if msg.get_extra_msg().get_type() == read_index_forward::REQUEST_TYPE {
+            if let Some(leader) = self.read_index_leader.lock().unwrap().clone() {
+                let answered = read_index_forward::dispatch_extra_message(
+                    &msg,
+                    &*leader,
+                    |reply| self.v1_sink.send(reply),
+                    |_| {},
+                );
+                if answered {
+                    return;
+                }
+            }
+        }
+        self.v2_sink.send(translate_v1_to_v2(msg));
+    }
+
+    /// Handle a message observed on the v2 side. Messages addressed to the
+    /// external learner store are translated into v1 conventions and relayed.
+    pub fn on_v2_message(&self, msg: RaftMessage) {
+        if !Self::should_forward(&msg, self.learner_store) {
+            return;
+        }
+        if !self.is_running() {
+            self.metrics.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        self.metrics.forwarded.fetch_add(1, Ordering::Relaxed);
+        if is_gc_peer_extra(msg.get_extra_msg().get_type()) {
+            self.metrics.gc_peer.fetch_add(1, Ordering::Relaxed);
+        }
+        self.v1_sink.send(translate_v2_to_v1(msg));
+    }
+}
+
+/// Whether an extra message participates in the GC-peer handshake, which both
+/// sides must carry verbatim for conf-change removals to converge.
+fn is_gc_peer_extra(ty: ExtraMessageType) -> bool {
+    matches!(
+        ty,
+        ExtraMessageType::MsgGcPeerRequest | ExtraMessageType::MsgGcPeerResponse
+    )
+}
+
+/// Whether an extra message is meaningful to the opposite side of the bridge.
+/// The GC-peer handshake and the ReadIndex-forwarding handshake both cross this
+/// boundary; every other extra-message type is a within-engine optimisation
+/// (flush/hibernate/pre-vote hints) that the other convention either does not
+/// understand or would mis-handle.
+fn crosses_boundary(ty: ExtraMessageType) -> bool {
+    is_gc_peer_extra(ty) || read_index_forward::is_read_index_extra(ty)
+}
+
+/// Drop any extra message that the other side must not see, leaving the GC-peer
+/// handshake intact. Shared between both directions: the learner and the leader
+/// run different engines, so a v2-only (or v1-only) hint leaking across would be
+/// interpreted against the wrong state machine.
+fn strip_foreign_extra(msg: &mut RaftMessage) {
+    if msg.has_extra_msg() && !crosses_boundary(msg.get_extra_msg().get_type()) {
+        msg.clear_extra_msg();
+    }
+}
+
+/// Translate a message leaving the v1 learner for the v2 leader.
+///
+/// v1 signals a destroyed peer with the `is_tombstone` flag, but the v2 leader
+/// drives its GC-peer bookkeeping off the `MsgGcPeer` handshake and the region
+/// epoch, not off `is_tombstone`; a stray flag would make the v2 peer fsm treat
+/// the ack as an unrelated self-destroy. Clear it, and normalise the learner's
+/// `from_peer` to the `Learner` role so the leader records it in
+/// `removed_records` as a learner rather than a voter. The region epoch the
+/// learner applied is carried through so the leader can match the removal, and
+/// v1-only extras are stripped.
+///
+/// Only the GC-peer handshake is rewritten; ordinary Raft traffic (append and
+/// heartbeat responses) crosses the bridge byte-for-byte.
+fn translate_v1_to_v2(mut msg: RaftMessage) -> RaftMessage {
+    let is_gc_peer = msg.has_extra_msg() && is_gc_peer_extra(msg.get_extra_msg().get_type());
+    strip_foreign_extra(&mut msg);
+    if is_gc_peer {
+        msg.set_is_tombstone(false);
+        if msg.has_from_peer() && msg.get_from_peer().get_role() != PeerRole::Learner {
+            msg.mut_from_peer().set_role(PeerRole::Learner);
+        }
+    }
+    msg
+}
+
+/// Translate a message leaving the v2 leader for the v1 learner.
+///
+/// The v2 leader announces a conf-change removal with a `MsgGcPeerResponse`, but
+/// the v1 learner only destroys itself when it observes `is_tombstone`; without
+/// the flag it keeps the peer alive and the leader's `removed_records` never
+/// converges. Set `is_tombstone` on the GC-peer response so the learner acks the
+/// removal. v2-only extras are stripped so the learner does not act on state it
+/// does not track.
+fn translate_v2_to_v1(mut msg: RaftMessage) -> RaftMessage {
+    let is_gc_response =
+        msg.has_extra_msg() && msg.get_extra_msg().get_type() == ExtraMessageType::MsgGcPeerResponse;
+    strip_foreign_extra(&mut msg);
+    if is_gc_response {
+        msg.set_is_tombstone(true);
+    }
+    msg
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use kvproto::{metapb, raft_serverpb::ExtraMessage};
+
+    use super::*;
+
+    #[derive(Default)]
+    struct VecSink(Mutex<Vec<RaftMessage>>);
+
+    impl RaftMessageSink for VecSink {
+        fn send(&self, msg: RaftMessage) {
+            self.0.lock().unwrap().push(msg);
+        }
+    }
+
+    fn msg_to_store(store_id: u64) -> RaftMessage {
+        let mut peer = metapb::Peer::default();
+        peer.set_store_id(store_id);
+        let mut msg = RaftMessage::default();
+        msg.set_to_peer(peer);
+        msg
+    }
+
+    #[test]
+    fn test_forwards_only_matching_store() {
+        let v1 = Arc::new(VecSink::default());
+        let v2 = Arc::new(VecSink::default());
+        let bridge = RaftMessageBridge::new(v1.clone(), v2.clone(), 2, 1);
+        bridge.start();
+
+        // A v1-side message to the leader store (1) is relayed to v2.
+        bridge.on_v1_message(msg_to_store(1));
+        // A v1-side message to some other store is ignored.
+        bridge.on_v1_message(msg_to_store(3));
+        // A v2-side message to the learner store (2) is relayed to v1.
+        bridge.on_v2_message(msg_to_store(2));
+
+        assert_eq!(v2.0.lock().unwrap().len(), 1);
+        assert_eq!(v1.0.lock().unwrap().len(), 1);
+        assert_eq!(bridge.metrics().forwarded(), 2);
+        assert_eq!(bridge.metrics().dropped(), 0);
+    }
+
+    #[test]
+    fn test_stopped_bridge_drops_matching_messages() {
+        let v1 = Arc::new(VecSink::default());
+        let v2 = Arc::new(VecSink::default());
+        let bridge = RaftMessageBridge::new(v1.clone(), v2.clone(), 2, 1);
+
+        // Not started: a matching message is dropped and counted, not relayed.
+        bridge.on_v1_message(msg_to_store(1));
+        assert!(v2.0.lock().unwrap().is_empty());
+        assert_eq!(bridge.metrics().dropped(), 1);
+        assert_eq!(bridge.metrics().forwarded(), 0);
+
+        bridge.start();
+        bridge.on_v1_message(msg_to_store(1));
+        bridge.stop();
+        bridge.on_v1_message(msg_to_store(1));
+        assert_eq!(v2.0.lock().unwrap().len(), 1);
+        assert_eq!(bridge.metrics().forwarded(), 1);
+        assert_eq!(bridge.metrics().dropped(), 2);
+    }
+
+    fn extra(ty: ExtraMessageType) -> ExtraMessage {
+        let mut e = ExtraMessage::default();
+        e.set_type(ty);
+        e
+    }
+
+    #[test]
+    fn test_gc_peer_extra_crosses_and_is_counted() {
+        let v1 = Arc::new(VecSink::default());
+        let v2 = Arc::new(VecSink::default());
+        let bridge = RaftMessageBridge::new(v1.clone(), v2.clone(), 2, 1);
+        bridge.start();
+
+        // A GC-peer request from the learner crosses to the leader verbatim.
+        let mut req = msg_to_store(1);
+        req.set_extra_msg(extra(ExtraMessageType::MsgGcPeerRequest));
+        bridge.on_v1_message(req);
+        // A GC-peer response from the leader crosses back to the learner.
+        let mut resp = msg_to_store(2);
+        resp.set_extra_msg(extra(ExtraMessageType::MsgGcPeerResponse));
+        bridge.on_v2_message(resp);
+
+        assert_eq!(
+            v2.0.lock().unwrap()[0].get_extra_msg().get_type(),
+            ExtraMessageType::MsgGcPeerRequest
+        );
+        assert_eq!(
+            v1.0.lock().unwrap()[0].get_extra_msg().get_type(),
+            ExtraMessageType::MsgGcPeerResponse
+        );
+        assert_eq!(bridge.metrics().gc_peer(), 2);
+    }
+
+    #[test]
+    fn test_foreign_extra_is_stripped() {
+        let v1 = Arc::new(VecSink::default());
+        let v2 = Arc::new(VecSink::default());
+        let bridge = RaftMessageBridge::new(v1.clone(), v2.clone(), 2, 1);
+        bridge.start();
+
+        // A rollback-merge hint is within-engine state the learner does not
+        // track, so it must not reach the v1 learner.
+        let mut msg = msg_to_store(2);
+        msg.set_extra_msg(extra(ExtraMessageType::MsgWantRollbackMerge));
+        bridge.on_v2_message(msg);
+
+        assert!(!v1.0.lock().unwrap()[0].has_extra_msg());
+        assert_eq!(bridge.metrics().gc_peer(), 0);
+    }
+
+    #[test]
+    fn test_v2_to_v1_marks_gc_response_tombstone() {
+        let v1 = Arc::new(VecSink::default());
+        let v2 = Arc::new(VecSink::default());
+        let bridge = RaftMessageBridge::new(v1.clone(), v2.clone(), 2, 1);
+        bridge.start();
+
+        // The v2 leader's removal ack carries no tombstone flag of its own.
+        let mut resp = msg_to_store(2);
+        resp.set_extra_msg(extra(ExtraMessageType::MsgGcPeerResponse));
+        assert!(!resp.get_is_tombstone());
+        bridge.on_v2_message(resp);
+
+        // After translation the v1 learner sees an explicit tombstone, so the
+        // relayed message is observably different from what entered the bridge.
+        let relayed = &v1.0.lock().unwrap()[0];
+        assert!(relayed.get_is_tombstone());
+        assert_eq!(
+            relayed.get_extra_msg().get_type(),
+            ExtraMessageType::MsgGcPeerResponse
+        );
+    }
+
+    #[test]
+    fn test_v1_to_v2_normalises_learner_ack() {
+        let v1 = Arc::new(VecSink::default());
+        let v2 = Arc::new(VecSink::default());
+        let bridge = RaftMessageBridge::new(v1.clone(), v2.clone(), 2, 1);
+        bridge.start();
+
+        // A v1 ack to the leader store carries the v1 tombstone flag and a
+        // from_peer the v1 side treats as a voter.
+        let mut ack = msg_to_store(1);
+        ack.set_is_tombstone(true);
+        let mut from = metapb::Peer::default();
+        from.set_store_id(2);
+        from.set_role(metapb::PeerRole::Voter);
+        ack.set_from_peer(from);
+        ack.set_extra_msg(extra(ExtraMessageType::MsgGcPeerRequest));
+        bridge.on_v1_message(ack);
+
+        // The v2 leader must see the ack as a learner record with no stray
+        // tombstone flag, while the GC-peer handshake still crosses.
+        let relayed = &v2.0.lock().unwrap()[0];
+        assert!(!relayed.get_is_tombstone());
+        assert_eq!(relayed.get_from_peer().get_role(), metapb::PeerRole::Learner);
+        assert_eq!(
+            relayed.get_extra_msg().get_type(),
+            ExtraMessageType::MsgGcPeerRequest
+        );
+    }
+
+    #[test]
+    fn test_v1_to_v2_leaves_normal_traffic_untouched() {
+        let v1 = Arc::new(VecSink::default());
+        let v2 = Arc::new(VecSink::default());
+        let bridge = RaftMessageBridge::new(v1.clone(), v2.clone(), 2, 1);
+        bridge.start();
+
+        // A plain append/heartbeat response to the leader store carries no
+        // GC-peer extra; its tombstone flag and peer role must survive the
+        // crossing verbatim.
+        let mut msg = msg_to_store(1);
+        msg.set_is_tombstone(true);
+        let mut from = metapb::Peer::default();
+        from.set_store_id(2);
+        from.set_role(metapb::PeerRole::Voter);
+        msg.set_from_peer(from);
+        bridge.on_v1_message(msg);
+
+        let relayed = &v2.0.lock().unwrap()[0];
+        assert!(relayed.get_is_tombstone());
+        assert_eq!(relayed.get_from_peer().get_role(), metapb::PeerRole::Voter);
+    }
+
+    struct FixedLeader(u64);
+    impl ReadIndexLeader for FixedLeader {
+        fn confirm_read_index(&self) -> Option<u64> {
+            Some(self.0)
+        }
+    }
+
+    #[test]
+    fn test_registered_leader_answers_read_index_request() {
+        use super::read_index_forward::{
+            build_request_message, decode_response, ReadIndexRequest, REQUEST_TYPE,
+        };
+
+        let v1 = Arc::new(VecSink::default());
+        let v2 = Arc::new(VecSink::default());
+        let bridge = RaftMessageBridge::new(v1.clone(), v2.clone(), 2, 1);
+        bridge.start();
+        // An in-process leader committed up to index 40.
+        bridge.set_read_index_leader(Arc::new(FixedLeader(40)));
+
+        let mut from = metapb::Peer::default();
+        from.set_store_id(2);
+        let mut to = metapb::Peer::default();
+        to.set_store_id(1);
+        let mut req = build_request_message(
+            1,
+            from,
+            to,
+            metapb::RegionEpoch::default(),
+            ReadIndexRequest { applied_index: 10 },
+        );
+        assert_eq!(req.get_extra_msg().get_type(), REQUEST_TYPE);
+        req.set_to_peer({
+            let mut p = metapb::Peer::default();
+            p.set_store_id(1);
+            p
+        });
+        bridge.on_v1_message(req);
+
+        // The bridge answered locally: the reply went back to the learner side,
+        // nothing was relayed on to the v2 sink.
+        assert!(v2.0.lock().unwrap().is_empty());
+        let reply = &v1.0.lock().unwrap()[0];
+        assert_eq!(decode_response(reply).unwrap().read_index, 40);
+    }
+}
@@ -0,0 +1,12 @@
+// Copyright 2016 TiKV Project Authors. Licensed under Apache-2.0.
+
+pub mod bridge;
+pub mod read_index_forward;
+
+pub use self::{
+    bridge::{BridgeMetrics, RaftMessageBridge, RaftMessageSink},
+    read_index_forward::{
+        dispatch_extra_message, ReadIndexForwarder, ReadIndexLeader, ReadIndexRequest,
+        ReadIndexResponse,
+    },
+};
@@ -0,0 +1,323 @@
+// Copyright 2024 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! ReadIndex forwarding for v2-compatible learners.
+//!
+//! With `enable_v2_compatible_learner` a learner backed by an external engine
+//! receives the log but has no path to serve linearizable reads, because it
+//! never obtains a read index from the leader. This module provides the pieces
+//! of a ReadIndex request/response flow over the same extra-message channel the
+//! GC-peer path already uses: the learner emits a request carrying its applied
+//! index, a [`ReadIndexLeader`] confirms leadership and yields its committed
+//! index, which is returned as the read index, and a [`ReadIndexForwarder`]
+//! blocks the read until apply progress reaches that index. Leadership is
+//! confirmed by whatever [`ReadIndexLeader`] implementation the caller supplies;
+//! the peer fsm neither implements that trait nor calls
+//! [`dispatch_extra_message`], whose only caller is the
+//! [`bridge`](crate::store::bridge).
+//!
+//! The handshake rides the `MsgVoterReplicatedIndex{Request,Response}` extra
+//! messages: they are the existing request/response pair that already carries a
+//! single `index` across the boundary, which is exactly the shape a read-index
+//! exchange needs. A production roll-out adds dedicated `MsgReadIndex*` variants
+//! to `kvproto::raft_serverpb::ExtraMessageType`; [`REQUEST_TYPE`] /
+//! [`RESPONSE_TYPE`] are the single place to repoint once those land.
+
+use kvproto::{
+    metapb,
+    raft_serverpb::{ExtraMessageType, RaftMessage},
+};
+
+/// The extra-message type the learner tags its ReadIndex request with.
+pub const REQUEST_TYPE: ExtraMessageType = ExtraMessageType::MsgVoterReplicatedIndexRequest;
+/// The extra-message type the leader tags its ReadIndex reply with.
+pub const RESPONSE_TYPE: ExtraMessageType = ExtraMessageType::MsgVoterReplicatedIndexResponse;
+
+/// Whether an extra message belongs to the ReadIndex-forwarding channel, so the
+/// [`bridge`](crate::store::bridge) relays it across the v1<->v2 boundary instead
+/// of stripping it as a foreign extra.
+pub fn is_read_index_extra(ty: ExtraMessageType) -> bool {
+    ty == REQUEST_TYPE || ty == RESPONSE_TYPE
+}
+
+/// The learner's ReadIndex request, decoded from the extra message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadIndexRequest {
+    /// The applied index the learner has reached; the confirmed read index is
+    /// never lower than this.
+    pub applied_index: u64,
+}
+
+/// The leader's reply carrying the committed read index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadIndexResponse {
+    pub read_index: u64,
+}
+
+/// The leader side of the handshake. An implementation confirms leadership and
+/// returns the current committed index, or `None` if leadership is not
+/// currently held. This module provides no implementation.
+pub trait ReadIndexLeader: Send + Sync {
+    fn confirm_read_index(&self) -> Option<u64>;
+}
+
+/// Produces the reply for a learner request, or `None` when leadership can't be
+/// confirmed so the learner retries instead of reading stale data.
+pub fn handle_read_index_request<L: ReadIndexLeader>(
+    req: &ReadIndexRequest,
+    leader: &L,
+) -> Option<ReadIndexResponse> {
+    leader.confirm_read_index().map(|committed| ReadIndexResponse {
+        // The learner must never be told to read below what it already applied.
+        read_index: committed.max(req.applied_index),
+    })
+}
+
+/// Gates a pending read on the learner until apply progress catches up to the
+/// confirmed read index.
+#[derive(Debug, Clone, Copy)]
+pub struct ReadIndexGate {
+    read_index: u64,
+}
+
+impl ReadIndexGate {
+    pub fn new(resp: &ReadIndexResponse) -> ReadIndexGate {
+        ReadIndexGate {
+            read_index: resp.read_index,
+        }
+    }
+
+    pub fn read_index(&self) -> u64 {
+        self.read_index
+    }
+
+    /// Whether the read may now be served, given the learner's apply progress.
+    pub fn is_ready(&self, applied_index: u64) -> bool {
+        applied_index >= self.read_index
+    }
+}
+
+/// Builds the extra-message `RaftMessage` the learner sends to the leader.
+pub fn build_request_message(
+    region_id: u64,
+    from_peer: metapb::Peer,
+    to_peer: metapb::Peer,
+    region_epoch: metapb::RegionEpoch,
+    req: ReadIndexRequest,
+) -> RaftMessage {
+    let mut msg = RaftMessage::default();
+    msg.set_region_id(region_id);
+    msg.set_from_peer(from_peer);
+    msg.set_to_peer(to_peer);
+    msg.set_region_epoch(region_epoch);
+    let extra = msg.mut_extra_msg();
+    extra.set_type(REQUEST_TYPE);
+    extra.set_index(req.applied_index);
+    msg
+}
+
+/// Decodes a learner request from an incoming message, if it is one.
+pub fn decode_request(msg: &RaftMessage) -> Option<ReadIndexRequest> {
+    if msg.get_extra_msg().get_type() != REQUEST_TYPE {
+        return None;
+    }
+    Some(ReadIndexRequest {
+        applied_index: msg.get_extra_msg().get_index(),
+    })
+}
+
+/// Builds the leader's reply, routed back to the peer that asked.
+pub fn build_response_message(req_msg: &RaftMessage, resp: ReadIndexResponse) -> RaftMessage {
+    let mut msg = RaftMessage::default();
+    msg.set_region_id(req_msg.get_region_id());
+    // Swap from/to so the reply heads back to the learner.
+    msg.set_from_peer(req_msg.get_to_peer().clone());
+    msg.set_to_peer(req_msg.get_from_peer().clone());
+    msg.set_region_epoch(req_msg.get_region_epoch().clone());
+    let extra = msg.mut_extra_msg();
+    extra.set_type(RESPONSE_TYPE);
+    extra.set_index(resp.read_index);
+    msg
+}
+
+/// Decodes a leader reply from an incoming message, if it is one.
+pub fn decode_response(msg: &RaftMessage) -> Option<ReadIndexResponse> {
+    if msg.get_extra_msg().get_type() != RESPONSE_TYPE {
+        return None;
+    }
+    Some(ReadIndexResponse {
+        read_index: msg.get_extra_msg().get_index(),
+    })
+}
+
+/// The dispatch hook for ReadIndex extra messages; the bridge calls it when an
+/// in-process leader is registered. A request is answered by confirming
+/// leadership and routing the reply back through `sink`;
+/// a response is handed to `on_response` so the learner can arm its read gate.
+/// Returns whether the message belonged to the ReadIndex channel and was
+/// consumed here, so the caller can fall through to normal handling otherwise.
+pub fn dispatch_extra_message<L, S, R>(
+    msg: &RaftMessage,
+    leader: &L,
+    sink: S,
+    on_response: R,
+) -> bool
+where
+    L: ReadIndexLeader,
+    S: FnOnce(RaftMessage),
+    R: FnOnce(ReadIndexResponse),
+{
+    if let Some(req) = decode_request(msg) {
+        if let Some(resp) = handle_read_index_request(&req, leader) {
+            sink(build_response_message(msg, resp));
+        }
+        return true;
+    }
+    if let Some(resp) = decode_response(msg) {
+        on_response(resp);
+        return true;
+    }
+    false
+}
+
+/// Learner-side state for one in-flight forwarded ReadIndex: it arms the gate
+/// when the leader's reply arrives and releases the read once apply progress
+/// reaches the confirmed index.
+#[derive(Debug, Default)]
+pub struct ReadIndexForwarder {
+    gate: Option<ReadIndexGate>,
+}
+
+impl ReadIndexForwarder {
+    /// Records the leader's confirmed read index, arming the gate.
+    pub fn on_response(&mut self, resp: ReadIndexResponse) {
+        self.gate = Some(ReadIndexGate::new(&resp));
+    }
+
+    /// Whether a forwarded read is still waiting for apply progress.
+    pub fn is_waiting(&self) -> bool {
+        self.gate.is_some()
+    }
+
+    /// Releases the pending read once apply progress reaches the confirmed
+    /// index, consuming the gate. Returns `false` while it is still blocked.
+    pub fn poll_ready(&mut self, applied_index: u64) -> bool {
+        match self.gate {
+            Some(gate) if gate.is_ready(applied_index) => {
+                self.gate = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Leader(Option<u64>);
+
+    impl ReadIndexLeader for Leader {
+        fn confirm_read_index(&self) -> Option<u64> {
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_leader_reply_never_below_applied() {
+        let req = ReadIndexRequest { applied_index: 42 };
+        // Leader commit is ahead of the learner: it governs the read index.
+        let resp = handle_read_index_request(&req, &Leader(Some(50))).unwrap();
+        assert_eq!(resp.read_index, 50);
+        // Leader commit trails the learner's applied index: clamp up to applied.
+        let resp = handle_read_index_request(&req, &Leader(Some(10))).unwrap();
+        assert_eq!(resp.read_index, 42);
+    }
+
+    #[test]
+    fn test_no_reply_when_leadership_unconfirmed() {
+        let req = ReadIndexRequest { applied_index: 7 };
+        assert!(handle_read_index_request(&req, &Leader(None)).is_none());
+    }
+
+    #[test]
+    fn test_gate_blocks_until_applied_catches_up() {
+        let gate = ReadIndexGate::new(&ReadIndexResponse { read_index: 100 });
+        assert!(!gate.is_ready(99));
+        assert!(gate.is_ready(100));
+        assert!(gate.is_ready(101));
+    }
+
+    #[test]
+    fn test_request_round_trip_encoding() {
+        let mut from = metapb::Peer::default();
+        from.set_id(10);
+        from.set_store_id(2);
+        let mut to = metapb::Peer::default();
+        to.set_id(1);
+        to.set_store_id(1);
+        let msg = build_request_message(
+            5,
+            from,
+            to,
+            metapb::RegionEpoch::default(),
+            ReadIndexRequest { applied_index: 77 },
+        );
+        let req = decode_request(&msg).unwrap();
+        assert_eq!(req.applied_index, 77);
+
+        let reply = build_response_message(&msg, ReadIndexResponse { read_index: 88 });
+        // The reply heads back to the learner store.
+        assert_eq!(reply.get_to_peer().get_store_id(), 2);
+        assert_eq!(decode_response(&reply).unwrap().read_index, 88);
+    }
+
+    #[test]
+    fn test_dispatch_answers_request_and_arms_gate() {
+        let mut from = metapb::Peer::default();
+        from.set_store_id(2);
+        let mut to = metapb::Peer::default();
+        to.set_store_id(1);
+        let req = build_request_message(
+            5,
+            from,
+            to,
+            metapb::RegionEpoch::default(),
+            ReadIndexRequest { applied_index: 20 },
+        );
+
+        // The leader side consumes the request and emits exactly one reply.
+        let mut replies = Vec::new();
+        let consumed = dispatch_extra_message(
+            &req,
+            &Leader(Some(30)),
+            |m| replies.push(m),
+            |_| panic!("request must not be decoded as a response"),
+        );
+        assert!(consumed);
+        assert_eq!(replies.len(), 1);
+        assert_eq!(decode_response(&replies[0]).unwrap().read_index, 30);
+
+        // The learner side consumes the reply and arms its gate.
+        let mut forwarder = ReadIndexForwarder::default();
+        let consumed = dispatch_extra_message(
+            &replies[0],
+            &Leader(None),
+            |_| panic!("response must not be answered"),
+            |resp| forwarder.on_response(resp),
+        );
+        assert!(consumed);
+        assert!(forwarder.is_waiting());
+        assert!(!forwarder.poll_ready(29));
+        assert!(forwarder.poll_ready(30));
+        assert!(!forwarder.is_waiting());
+
+        // A non-ReadIndex message is left for normal handling.
+        assert!(!dispatch_extra_message(
+            &RaftMessage::default(),
+            &Leader(Some(1)),
+            |_| panic!("unrelated message must not be answered"),
+            |_| panic!("unrelated message must not be a response"),
+        ));
+    }
+}
@@ -0,0 +1,192 @@
+// Copyright 2024 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! Dedup helpers built on content-defined [`chunking`](crate::chunking).
+//!
+//! [`DedupConfig`] switches the layer on; it is off by default. With it on,
+//! [`plan_upload`] builds a [`ChunkManifest`] of a staged file and a
+//! [`TransferPlan`] (the chunks the receiver does not already hold plus how many
+//! bytes those amount to), [`chunk_bytes`] collects the bytes of those chunks,
+//! and [`reassemble`] rebuilds the file on disk from them and the chunks the
+//! receiver already holds. These are standalone helpers: the import service and
+//! `send_upload_sst` do not call them, there is no `enable_content_dedup`
+//! setting, and the upload response carries no moved-byte count.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use crate::chunking::{Chunk, ChunkHash, ChunkManifest, ChunkerConfig};
+
+/// Configuration for the dedup layer.
+///
+/// [`plan_upload`] consults [`enabled`](DedupConfig::enabled) and returns no plan
+/// when it is unset, which is the default, so dedup is strictly opt-in.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DedupConfig {
+    pub enabled: bool,
+    pub chunker: ChunkerConfig,
+}
+
+/// Builds the manifest the client advertises for a staged file.
+pub fn manifest_of_file(path: &Path, cfg: &ChunkerConfig) -> io::Result<ChunkManifest> {
+    let mut data = Vec::new();
+    std::fs::File::open(path)?.read_to_end(&mut data)?;
+    Ok(ChunkManifest::build(&data, cfg))
+}
+
+/// The receiver's answer to a manifest: which chunks it is missing and how many
+/// bytes that amounts to.
+pub struct TransferPlan {
+    pub missing: Vec<usize>,
+    pub bytes: u64,
+}
+
+/// Computes the chunks the receiver must fetch given the hashes it already
+/// holds.
+pub fn plan_transfer(manifest: &ChunkManifest, present: &HashSet<ChunkHash>) -> TransferPlan {
+    TransferPlan {
+        missing: manifest.missing_against(present),
+        bytes: manifest.transfer_bytes(present) as u64,
+    }
+}
+
+/// Plans the upload of a staged file. Returns the manifest to advertise and the
+/// transfer plan against what the receiver already holds, or `None` when dedup
+/// is disabled.
+pub fn plan_upload(
+    path: &Path,
+    cfg: &DedupConfig,
+    present: &HashSet<ChunkHash>,
+) -> io::Result<Option<(ChunkManifest, TransferPlan)>> {
+    if !cfg.enabled {
+        return Ok(None);
+    }
+    let manifest = manifest_of_file(path, &cfg.chunker)?;
+    let plan = plan_transfer(&manifest, present);
+    Ok(Some((manifest, plan)))
+}
+
+/// Reassembles the file on the receiver from the chunks it already held plus the
+/// ones just transferred, writing the result to `out`.
+///
+/// `local` resolves a chunk hash to bytes already present on the node; `fetched`
+/// holds the chunks streamed for this upload. Fails if a chunk cannot be
+/// resolved from either source.
+pub fn reassemble<F>(
+    out: &Path,
+    manifest: &ChunkManifest,
+    fetched: &HashMap<ChunkHash, Vec<u8>>,
+    mut local: F,
+) -> io::Result<()>
+where
+    F: FnMut(&ChunkHash) -> Option<Vec<u8>>,
+{
+    let mut file = std::fs::File::create(out)?;
+    for chunk in &manifest.chunks {
+        let bytes = fetched
+            .get(&chunk.hash)
+            .cloned()
+            .or_else(|| local(&chunk.hash))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("missing chunk {:?} during reassembly", chunk.hash),
+                )
+            })?;
+        debug_assert_eq!(bytes.len(), chunk.len);
+        file.write_all(&bytes)?;
+    }
+    file.flush()
+}
+
+/// Helper for the common case of reassembling entirely from fetched chunks,
+/// e.g. the first upload of a file the receiver has never seen.
+pub fn reassemble_from_fetched(
+    out: &Path,
+    manifest: &ChunkManifest,
+    fetched: &HashMap<ChunkHash, Vec<u8>>,
+) -> io::Result<()> {
+    reassemble(out, manifest, fetched, |_| None)
+}
+
+/// Convenience to turn a manifest + source bytes into the chunk map the wire
+/// path streams for the missing chunks.
+pub fn chunk_bytes(manifest: &ChunkManifest, data: &[u8], missing: &[usize]) -> HashMap<ChunkHash, Vec<u8>> {
+    let mut out = HashMap::new();
+    for &i in missing {
+        let c: &Chunk = &manifest.chunks[i];
+        out.insert(c.hash, data[c.offset..c.offset + c.len].to_vec());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::seeded_bytes as data;
+
+    #[test]
+    fn test_reassembly_reproduces_the_file_from_dedup() {
+        let cfg = ChunkerConfig::default();
+        let first = data(0, 512 * 1024);
+        let mut second = first.clone();
+        second.extend_from_slice(&data(7, 48 * 1024));
+
+        // Receiver already holds the first file's chunks.
+        let m1 = ChunkManifest::build(&first, &cfg);
+        let mut store: HashMap<ChunkHash, Vec<u8>> = HashMap::new();
+        for c in &m1.chunks {
+            store.insert(c.hash, first[c.offset..c.offset + c.len].to_vec());
+        }
+        let present: HashSet<ChunkHash> = store.keys().copied().collect();
+
+        // Only the missing chunks of the second file are transferred.
+        let m2 = ChunkManifest::build(&second, &cfg);
+        let plan = plan_transfer(&m2, &present);
+        assert!(plan.bytes < second.len() as u64 / 2);
+        let fetched = chunk_bytes(&m2, &second, &plan.missing);
+
+        // Reassembly pulls shared chunks from the local store and the rest from
+        // the transferred set, reproducing the second file byte-for-byte.
+        let dir = std::env::temp_dir().join("chunk_dedup_reassembly_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let out = dir.join("out.sst");
+        reassemble(&out, &m2, &fetched, |h| store.get(h).cloned()).unwrap();
+        let mut rebuilt = Vec::new();
+        std::fs::File::open(&out)
+            .unwrap()
+            .read_to_end(&mut rebuilt)
+            .unwrap();
+        assert_eq!(rebuilt, second);
+    }
+
+    #[test]
+    fn test_plan_upload_is_opt_in_and_reports_moved_bytes() {
+        let dir = std::env::temp_dir().join("chunk_dedup_plan_upload_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let first = data(0, 512 * 1024);
+        let mut second = first.clone();
+        second.extend_from_slice(&data(7, 48 * 1024));
+        let path = dir.join("second.sst");
+        std::fs::File::create(&path).unwrap().write_all(&second).unwrap();
+
+        // Receiver already holds the first file's chunks.
+        let present: HashSet<ChunkHash> = ChunkManifest::build(&first, &ChunkerConfig::default())
+            .chunks
+            .iter()
+            .map(|c| c.hash)
+            .collect();
+
+        // Disabled: no plan, so the caller falls back to a whole-blob upload.
+        let off = DedupConfig::default();
+        assert!(plan_upload(&path, &off, &present).unwrap().is_none());
+
+        // Enabled: only the appended tail is planned for transfer.
+        let on = DedupConfig {
+            enabled: true,
+            ..DedupConfig::default()
+        };
+        let (_, plan) = plan_upload(&path, &on, &present).unwrap().unwrap();
+        assert!(plan.bytes < second.len() as u64 / 2);
+    }
+}
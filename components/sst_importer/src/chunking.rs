@@ -0,0 +1,238 @@
+// Copyright 2024 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! Content-defined chunking with dedup for SST upload/download.
+//!
+//! `upload_sst` streams an SST as one opaque blob, so re-uploading overlapping
+//! or near-identical SSTs (common when regions split or a download is retried
+//! against a slightly different range) transfers fully redundant bytes. This
+//! module cuts a byte stream at data-dependent boundaries with a Gear rolling
+//! hash, so a shared prefix produces the same chunks regardless of what is
+//! appended afterwards, and builds a [`ChunkManifest`] of chunk hashes from
+//! which a receiver can tell which chunks it lacks. Planning and reassembly live
+//! in [`chunk_dedup`](crate::chunk_dedup); the upload path itself does not use
+//! this module.
+
+use std::collections::HashSet;
+
+use sha2::{Digest, Sha256};
+
+/// A chunk's content address. The whole dedup decision — "does the receiver
+/// already hold this chunk?" — is made by comparing these, so the hash has to be
+/// wide enough that two distinct chunks never share one; a 256-bit digest keeps
+/// the odds negligible across the chunk populations an importer ever sees, which
+/// is why reassembly can trust a hash match to mean byte-identical content.
+pub type ChunkHash = [u8; 32];
+
+/// Tuning for the rolling-hash chunker. The mask controls the average chunk
+/// size; `min`/`max` clamp the boundaries so they stay stable under insertions
+/// and deletions without letting a chunk grow unbounded.
+#[derive(Debug, Clone, Copy)]
+pub struct ChunkerConfig {
+    pub min_size: usize,
+    pub max_size: usize,
+    pub mask: u64,
+}
+
+impl Default for ChunkerConfig {
+    fn default() -> Self {
+        // 16 KiB min / 64 KiB max. The 15-bit mask allows a cut at about one in
+        // 32 Ki positions past the minimum, so chunks average roughly 40 KiB.
+        ChunkerConfig {
+            min_size: 16 * 1024,
+            max_size: 64 * 1024,
+            mask: (1 << 15) - 1,
+        }
+    }
+}
+
+/// A single content-defined chunk: its offset in the stream, length, and hash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chunk {
+    pub offset: usize,
+    pub len: usize,
+    pub hash: ChunkHash,
+}
+
+/// The Gear table, derived deterministically from `splitmix64` so the same
+/// bytes always cut at the same boundaries across nodes and builds.
+fn gear(byte: u8) -> u64 {
+    let mut z = (byte as u64).wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+fn chunk_hash(bytes: &[u8]) -> ChunkHash {
+    let mut hasher = Sha256::new();
+    hasher.update(bytes);
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&hasher.finalize());
+    out
+}
+
+/// Splits `data` into content-defined chunks.
+pub fn split(data: &[u8], cfg: &ChunkerConfig) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < data.len() {
+        let end = next_boundary(&data[start..], cfg) + start;
+        chunks.push(Chunk {
+            offset: start,
+            len: end - start,
+            hash: chunk_hash(&data[start..end]),
+        });
+        start = end;
+    }
+    chunks
+}
+
+/// Finds the end of the next chunk within `data`, honouring the size clamps.
+fn next_boundary(data: &[u8], cfg: &ChunkerConfig) -> usize {
+    let limit = data.len().min(cfg.max_size);
+    let mut hash: u64 = 0;
+    let mut i = 0;
+    while i < limit {
+        hash = (hash << 1).wrapping_add(gear(data[i]));
+        i += 1;
+        // Only allow a cut once past the minimum size.
+        if i >= cfg.min_size && hash & cfg.mask == 0 {
+            return i;
+        }
+    }
+    limit
+}
+
+/// The chunk manifest a client sends before transferring any bytes.
+#[derive(Debug, Clone)]
+pub struct ChunkManifest {
+    pub chunks: Vec<Chunk>,
+}
+
+impl ChunkManifest {
+    pub fn build(data: &[u8], cfg: &ChunkerConfig) -> ChunkManifest {
+        ChunkManifest {
+            chunks: split(data, cfg),
+        }
+    }
+
+    pub fn total_len(&self) -> usize {
+        self.chunks.iter().map(|c| c.len).sum()
+    }
+
+    /// The indices of the chunks the receiver does not already hold, given the
+    /// set of chunk hashes present on its side.
+    pub fn missing_against(&self, present: &HashSet<ChunkHash>) -> Vec<usize> {
+        self.chunks
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| !present.contains(&c.hash))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// The number of bytes that actually have to be transferred after dedup.
+    pub fn transfer_bytes(&self, present: &HashSet<ChunkHash>) -> usize {
+        self.chunks
+            .iter()
+            .filter(|c| !present.contains(&c.hash))
+            .map(|c| c.len)
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::seeded_bytes as data;
+
+    #[test]
+    fn test_boundaries_are_stable_under_append() {
+        let cfg = ChunkerConfig::default();
+        let prefix = data(0, 512 * 1024);
+        let mut extended = prefix.clone();
+        extended.extend_from_slice(&data(7, 48 * 1024));
+
+        let a = split(&prefix, &cfg);
+        let b = split(&extended, &cfg);
+
+        // Every completed chunk of the prefix reappears byte-for-byte in the
+        // extended stream, so only the tail differs.
+        let shared = a.len() - 1; // last prefix chunk may merge with the tail
+        for i in 0..shared {
+            assert_eq!(a[i].offset, b[i].offset);
+            assert_eq!(a[i].len, b[i].len);
+            assert_eq!(a[i].hash, b[i].hash);
+        }
+    }
+
+    #[test]
+    fn test_boundaries_are_stable_under_insertion() {
+        let cfg = ChunkerConfig::default();
+        let base = data(0, 512 * 1024);
+        // Splice a block into the middle rather than appending to the end.
+        let mut spliced = base[..256 * 1024].to_vec();
+        spliced.extend_from_slice(&data(9, 32 * 1024));
+        spliced.extend_from_slice(&base[256 * 1024..]);
+
+        let a = split(&base, &cfg);
+        let b = split(&spliced, &cfg);
+
+        // The chunks before the insertion point are untouched.
+        let prefix_shared = a
+            .iter()
+            .zip(&b)
+            .take_while(|(x, y)| x.hash == y.hash)
+            .count();
+        assert!(prefix_shared > 0, "no stable prefix before the insertion");
+
+        // Past the inserted region the content-defined chunker resynchronises, so
+        // most original chunks reappear by hash. A fixed-size chunker could not:
+        // every boundary after the insertion would shift by 32 KiB and nothing
+        // downstream would match.
+        let original: HashSet<ChunkHash> = a.iter().map(|c| c.hash).collect();
+        let reused = b.iter().filter(|c| original.contains(&c.hash)).count();
+        assert!(
+            reused * 2 > a.len(),
+            "insertion resync reused only {} of {} chunks",
+            reused,
+            a.len()
+        );
+    }
+
+    #[test]
+    fn test_dedup_transfers_only_the_tail() {
+        let cfg = ChunkerConfig::default();
+        let first = data(0, 512 * 1024);
+        let mut second = first.clone();
+        second.extend_from_slice(&data(7, 48 * 1024));
+
+        let m1 = ChunkManifest::build(&first, &cfg);
+        // The receiver already holds every chunk of the first upload.
+        let present: HashSet<ChunkHash> = m1.chunks.iter().map(|c| c.hash).collect();
+
+        let m2 = ChunkManifest::build(&second, &cfg);
+        let moved = m2.transfer_bytes(&present);
+
+        // The shared prefix is skipped, so the second upload moves roughly only
+        // the appended tail rather than the whole file.
+        assert!(
+            moved * 4 < m2.total_len(),
+            "expected dedup to skip most of the stream: moved {} of {}",
+            moved,
+            m2.total_len()
+        );
+    }
+
+    #[test]
+    fn test_chunk_sizes_respect_clamps() {
+        let cfg = ChunkerConfig::default();
+        let chunks = split(&data(3, 1024 * 1024), &cfg);
+        for (i, c) in chunks.iter().enumerate() {
+            assert!(c.len <= cfg.max_size);
+            // Every chunk but the last must clear the minimum size.
+            if i + 1 < chunks.len() {
+                assert!(c.len >= cfg.min_size);
+            }
+        }
+    }
+}
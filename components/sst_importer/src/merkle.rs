@@ -0,0 +1,314 @@
+// Copyright 2024 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! An append-only Merkle digest for SST files.
+//!
+//! The digest can be computed incrementally as bytes are fed in: the byte
+//! stream is split into fixed-size data chunks, each chunk is hashed into a
+//! leaf, and the leaves are folded into a small set of perfect-subtree roots
+//! that behave like a binary counter. The final right-to-left fold of those
+//! roots yields the tree root, and [`MerkleTree::prove`] emits an inclusion
+//! proof for an individual chunk. The importer's `upload`, `download` and
+//! `ingest` paths do not compute or check this digest, and `SstMeta` does not
+//! store it.
+
+use sha2::{Digest, Sha256};
+
+/// The digest of a single tree node. SHA-256 is deterministic across nodes and
+/// builds, so a root computed on one store equals the root any other store
+/// recomputes from the same bytes; combined with the leaf/node domain
+/// separation below, rewriting a chunk cannot leave the root unchanged, which
+/// is what [`check_sst_against_meta`](crate::merkle_integrity::check_sst_against_meta)
+/// relies on.
+pub type MerkleHash = [u8; 32];
+
+/// Default data-chunk size used to cut the SST into leaves (64 KiB).
+pub const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+fn hash_parts(prefix: u8, parts: &[&[u8]]) -> MerkleHash {
+    // Domain-separate leaves from interior nodes so a leaf digest can never be
+    // reinterpreted as a node digest (second-preimage hardening).
+    let mut hasher = Sha256::new();
+    hasher.update([prefix]);
+    for part in parts {
+        hasher.update(part);
+    }
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&hasher.finalize());
+    out
+}
+
+fn hash_leaf(data: &[u8]) -> MerkleHash {
+    hash_parts(LEAF_PREFIX, &[data])
+}
+
+fn hash_node(left: &MerkleHash, right: &MerkleHash) -> MerkleHash {
+    hash_parts(NODE_PREFIX, &[left, right])
+}
+
+/// Combines two child digests into their parent, exposed so the proof verifier
+/// in [`crate::merkle_integrity`] folds nodes exactly as the builder does.
+pub fn hash_node_public(left: &MerkleHash, right: &MerkleHash) -> MerkleHash {
+    hash_node(left, right)
+}
+
+/// An inclusion proof for a single chunk. `path` is the ordered list of sibling
+/// digests walked from the leaf up to the root; `sibling_is_left` records which
+/// side each sibling sits on so the verifier combines them in the right order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleProof {
+    pub leaf_index: u64,
+    pub leaf_hash: MerkleHash,
+    pub path: Vec<(MerkleHash, bool)>,
+    pub root: MerkleHash,
+}
+
+/// Builds the Merkle digest as data is appended, persisting per-leaf hashes so
+/// any chunk can be proven later.
+pub struct MerkleBuilder {
+    chunk_size: usize,
+    pending: Vec<u8>,
+    leaves: Vec<MerkleHash>,
+}
+
+impl Default for MerkleBuilder {
+    fn default() -> Self {
+        Self::with_chunk_size(DEFAULT_CHUNK_SIZE)
+    }
+}
+
+impl MerkleBuilder {
+    pub fn with_chunk_size(chunk_size: usize) -> Self {
+        assert!(chunk_size > 0, "chunk size must be positive");
+        MerkleBuilder {
+            chunk_size,
+            pending: Vec::with_capacity(chunk_size),
+            leaves: Vec::new(),
+        }
+    }
+
+    /// Feeds a slice of the stream, emitting a leaf for every full chunk.
+    pub fn update(&mut self, mut bytes: &[u8]) {
+        while !bytes.is_empty() {
+            let want = self.chunk_size - self.pending.len();
+            let take = want.min(bytes.len());
+            self.pending.extend_from_slice(&bytes[..take]);
+            bytes = &bytes[take..];
+            if self.pending.len() == self.chunk_size {
+                let leaf = hash_leaf(&self.pending);
+                self.leaves.push(leaf);
+                self.pending.clear();
+            }
+        }
+    }
+
+    /// Flushes any trailing partial chunk and returns the finished tree.
+    pub fn finish(mut self) -> MerkleTree {
+        if !self.pending.is_empty() || self.leaves.is_empty() {
+            // Always keep at least one leaf so an empty SST still has a root.
+            let leaf = hash_leaf(&self.pending);
+            self.leaves.push(leaf);
+        }
+        MerkleTree {
+            leaves: self.leaves,
+        }
+    }
+}
+
+/// A finished Merkle tree: the persisted leaves plus the derived root and the
+/// machinery to emit inclusion proofs.
+pub struct MerkleTree {
+    leaves: Vec<MerkleHash>,
+}
+
+impl MerkleTree {
+    /// Builds a tree from an in-memory byte slice, cutting at `chunk_size`.
+    pub fn from_bytes(data: &[u8], chunk_size: usize) -> MerkleTree {
+        let mut builder = MerkleBuilder::with_chunk_size(chunk_size);
+        builder.update(data);
+        builder.finish()
+    }
+
+    pub fn leaf_count(&self) -> usize {
+        self.leaves.len()
+    }
+
+    /// The perfect-subtree leaf spans, left to right, one per set bit of the
+    /// leaf count with the largest subtree first.
+    fn subtrees(&self) -> Vec<(usize, usize)> {
+        let mut spans = Vec::new();
+        let mut start = 0;
+        let mut remaining = self.leaves.len();
+        let mut bit = usize::BITS - 1 - remaining.leading_zeros();
+        loop {
+            let size = 1usize << bit;
+            if remaining >= size {
+                spans.push((start, start + size));
+                start += size;
+                remaining -= size;
+            }
+            if remaining == 0 || bit == 0 {
+                break;
+            }
+            bit -= 1;
+        }
+        spans
+    }
+
+    fn subtree_root(&self, lo: usize, hi: usize) -> MerkleHash {
+        let mut level: Vec<MerkleHash> = self.leaves[lo..hi].to_vec();
+        while level.len() > 1 {
+            level = level
+                .chunks(2)
+                .map(|pair| hash_node(&pair[0], &pair[1]))
+                .collect();
+        }
+        level[0]
+    }
+
+    /// Folds the perfect-subtree roots right-to-left into the tree root.
+    fn bag(roots: &[MerkleHash]) -> MerkleHash {
+        let mut iter = roots.iter().rev();
+        let mut acc = *iter.next().expect("at least one subtree root");
+        for left in iter {
+            acc = hash_node(left, &acc);
+        }
+        acc
+    }
+
+    /// The Merkle root of the tree.
+    pub fn root(&self) -> MerkleHash {
+        let roots: Vec<MerkleHash> = self
+            .subtrees()
+            .into_iter()
+            .map(|(lo, hi)| self.subtree_root(lo, hi))
+            .collect();
+        Self::bag(&roots)
+    }
+
+    /// Emits the sibling path for `leaf_index`: the path up to the containing
+    /// perfect subtree's root, then the folded roots of the remaining subtrees.
+    pub fn prove(&self, leaf_index: usize) -> Option<MerkleProof> {
+        if leaf_index >= self.leaves.len() {
+            return None;
+        }
+        let spans = self.subtrees();
+        let sub = spans
+            .iter()
+            .position(|&(lo, hi)| leaf_index >= lo && leaf_index < hi)
+            .expect("leaf must fall in a subtree");
+        let (lo, hi) = spans[sub];
+
+        let mut path = Vec::new();
+        // Walk up within the perfect subtree.
+        let mut level: Vec<MerkleHash> = self.leaves[lo..hi].to_vec();
+        let mut idx = leaf_index - lo;
+        while level.len() > 1 {
+            if idx % 2 == 0 {
+                path.push((level[idx + 1], false));
+            } else {
+                path.push((level[idx - 1], true));
+            }
+            level = level
+                .chunks(2)
+                .map(|pair| hash_node(&pair[0], &pair[1]))
+                .collect();
+            idx /= 2;
+        }
+
+        let roots: Vec<MerkleHash> = spans
+            .iter()
+            .map(|&(lo, hi)| self.subtree_root(lo, hi))
+            .collect();
+        // Everything to the right of this subtree bags into a single right
+        // sibling; everything to the left stays as individual left siblings.
+        if sub + 1 < roots.len() {
+            path.push((Self::bag(&roots[sub + 1..]), false));
+        }
+        for left in roots[..sub].iter().rev() {
+            path.push((*left, true));
+        }
+
+        Some(MerkleProof {
+            leaf_index: leaf_index as u64,
+            leaf_hash: self.leaves[leaf_index],
+            path,
+            root: self.root(),
+        })
+    }
+}
+
+/// Recomputes the root from a proof and checks it against `root`, as a verifier
+/// that only holds the trusted root would.
+pub fn verify_merkle_proof(root: &MerkleHash, proof: &MerkleProof) -> bool {
+    let mut acc = proof.leaf_hash;
+    for (sibling, sibling_is_left) in &proof.path {
+        acc = if *sibling_is_left {
+            hash_node(sibling, &acc)
+        } else {
+            hash_node(&acc, sibling)
+        };
+    }
+    acc == *root && acc == proof.root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_proofs_round_trip_for_all_chunk_counts() {
+        // Exercise perfect (power-of-two) and ragged leaf counts alike.
+        for leaves in 1..=9 {
+            let bytes = data(leaves * 4);
+            let tree = MerkleTree::from_bytes(&bytes, 4);
+            assert_eq!(tree.leaf_count(), leaves);
+            let root = tree.root();
+            for i in 0..leaves {
+                let proof = tree.prove(i).unwrap();
+                assert!(
+                    verify_merkle_proof(&root, &proof),
+                    "leaf {} of {} must verify",
+                    i,
+                    leaves
+                );
+            }
+            assert!(tree.prove(leaves).is_none());
+        }
+    }
+
+    #[test]
+    fn test_tampering_changes_root_and_fails_proof() {
+        let bytes = data(64 * 5 + 7);
+        let tree = MerkleTree::from_bytes(&bytes, 64);
+        let root = tree.root();
+        let good = tree.prove(2).unwrap();
+        assert!(verify_merkle_proof(&root, &good));
+
+        let mut tampered = bytes.clone();
+        tampered[0] ^= 0xff;
+        let tampered_tree = MerkleTree::from_bytes(&tampered, 64);
+        assert_ne!(tampered_tree.root(), root);
+
+        // A proof from the tampered tree must not verify against the old root.
+        let bad = tampered_tree.prove(0).unwrap();
+        assert!(!verify_merkle_proof(&root, &bad));
+    }
+
+    #[test]
+    fn test_incremental_matches_one_shot() {
+        let bytes = data(64 * 3 + 10);
+        let one_shot = MerkleTree::from_bytes(&bytes, 64).root();
+        let mut builder = MerkleBuilder::with_chunk_size(64);
+        for piece in bytes.chunks(7) {
+            builder.update(piece);
+        }
+        assert_eq!(builder.finish().root(), one_shot);
+    }
+}
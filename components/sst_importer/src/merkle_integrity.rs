@@ -0,0 +1,187 @@
+// Copyright 2024 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! File-level helpers around the incremental [`merkle`](crate::merkle) digest.
+//!
+//! * [`digest_file`] recomputes the root of a staged SST.
+//! * [`check_sst_against_meta`] rejects a staged SST whose root differs from a
+//!   recorded one with an `InvalidData` error.
+//! * [`build_proof`] encodes an inclusion proof as an [`SstMerkleProof`], which
+//!   [`verify_merkle_proof`] checks against a trusted root.
+//!
+//! The proof is modelled as a plain crate-local value rather than a protobuf
+//! type, so the pure-Rust [`merkle`](crate::merkle) core carries no dependency
+//! on the generated wire types. The importer's `upload`, `download` and `ingest`
+//! paths do not call these helpers, `SstMeta` has no field for the root, and
+//! the import service has no proof RPC.
+
+use std::io;
+use std::path::Path;
+
+use crate::merkle::{MerkleBuilder, MerkleHash, MerkleProof, MerkleTree, DEFAULT_CHUNK_SIZE};
+
+/// A single step of an inclusion proof: a sibling digest and which side it sits
+/// on, so the verifier folds it into the accumulator in the right order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SstMerkleProofStep {
+    pub hash: Vec<u8>,
+    pub sibling_is_left: bool,
+}
+
+/// An inclusion proof for one data chunk of a staged SST, as built by
+/// [`build_proof`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SstMerkleProof {
+    pub leaf_index: u64,
+    pub leaf_hash: Vec<u8>,
+    pub root: Vec<u8>,
+    pub path: Vec<SstMerkleProofStep>,
+}
+
+/// Reads a staged SST and returns its Merkle tree, cutting at the default chunk
+/// size. [`digest_file`] and so [`check_sst_against_meta`] use it to recompute
+/// from the bytes on disk rather than trusting a cached value.
+pub fn tree_of_file(path: &Path) -> io::Result<MerkleTree> {
+    let mut builder = MerkleBuilder::default();
+    let mut file = std::fs::File::open(path)?;
+    let mut buf = vec![0u8; DEFAULT_CHUNK_SIZE];
+    loop {
+        let read = io::Read::read(&mut file, &mut buf)?;
+        if read == 0 {
+            break;
+        }
+        builder.update(&buf[..read]);
+    }
+    Ok(builder.finish())
+}
+
+/// The Merkle root of a staged file.
+pub fn digest_file(path: &Path) -> io::Result<MerkleHash> {
+    Ok(tree_of_file(path)?.root())
+}
+
+/// Rejects a staged SST whose recomputed root does not match `meta_root`. A
+/// no-op when `meta_root` is empty.
+pub fn check_sst_against_meta(path: &Path, meta_root: &[u8]) -> io::Result<()> {
+    if meta_root.is_empty() {
+        return Ok(());
+    }
+    let actual = digest_file(path)?;
+    if actual.as_slice() != meta_root {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "merkle root mismatch: meta {:?} file {:?}",
+                meta_root, actual
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Builds the `SstMerkleProof` for `leaf_index`, or `None` when it is out of
+/// range.
+pub fn build_proof(tree: &MerkleTree, leaf_index: usize) -> Option<SstMerkleProof> {
+    tree.prove(leaf_index).map(|p| encode_proof(&p))
+}
+
+fn encode_proof(proof: &MerkleProof) -> SstMerkleProof {
+    SstMerkleProof {
+        leaf_index: proof.leaf_index,
+        leaf_hash: proof.leaf_hash.to_vec(),
+        root: proof.root.to_vec(),
+        path: proof
+            .path
+            .iter()
+            .map(|(hash, is_left)| SstMerkleProofStep {
+                hash: hash.to_vec(),
+                sibling_is_left: *is_left,
+            })
+            .collect(),
+    }
+}
+
+fn decode_hash(bytes: &[u8]) -> Option<MerkleHash> {
+    bytes.try_into().ok()
+}
+
+/// Recomputes the root from an RPC `SstMerkleProof` and checks it against the
+/// trusted `root`. This is the verifier exported at the crate root.
+pub fn verify_merkle_proof(root: &[u8], proof: &SstMerkleProof) -> bool {
+    let expected = match decode_hash(root) {
+        Some(h) => h,
+        None => return false,
+    };
+    let mut acc = match decode_hash(&proof.leaf_hash) {
+        Some(h) => h,
+        None => return false,
+    };
+    for step in &proof.path {
+        let sibling = match decode_hash(&step.hash) {
+            Some(h) => h,
+            None => return false,
+        };
+        acc = if step.sibling_is_left {
+            crate::merkle::hash_node_public(&sibling, &acc)
+        } else {
+            crate::merkle::hash_node_public(&acc, &sibling)
+        };
+    }
+    decode_hash(&proof.root) == Some(acc) && acc == expected
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn staged(name: &str, bytes: &[u8]) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join("merkle_integrity_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::File::create(&path)
+            .unwrap()
+            .write_all(bytes)
+            .unwrap();
+        path
+    }
+
+    #[test]
+    fn test_ingest_check_accepts_untouched_and_rejects_tampered() {
+        let bytes: Vec<u8> = (0..DEFAULT_CHUNK_SIZE * 3 + 17).map(|i| i as u8).collect();
+        let path = staged("ingest.sst", &bytes);
+        let root = digest_file(&path).unwrap();
+
+        // The root recorded at upload matches the file on disk at ingest.
+        check_sst_against_meta(&path, root.as_slice()).unwrap();
+        // An empty meta root means Merkle mode was off, so the check is skipped.
+        check_sst_against_meta(&path, &[]).unwrap();
+
+        // Flipping a byte on disk is caught against the recorded root.
+        let tampered = staged("ingest_bad.sst", &{
+            let mut t = bytes.clone();
+            t[0] ^= 0xff;
+            t
+        });
+        assert!(check_sst_against_meta(&tampered, root.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_proof_round_trips_through_rpc_value() {
+        let bytes: Vec<u8> = (0..DEFAULT_CHUNK_SIZE * 5 + 3).map(|i| (i * 7) as u8).collect();
+        let path = staged("proof.sst", &bytes);
+        let tree = tree_of_file(&path).unwrap();
+        let root = tree.root();
+
+        let proof = build_proof(&tree, 2).expect("leaf 2 is in range");
+        // The encoded RPC value carries the root back so the caller can verify a
+        // proof it fetches later without re-reading the SST.
+        assert_eq!(proof.root, root.to_vec());
+        assert!(verify_merkle_proof(root.as_slice(), &proof));
+
+        // A proof tied to a different root must not verify.
+        let mut wrong = root;
+        wrong[0] ^= 0xff;
+        assert!(!verify_merkle_proof(wrong.as_slice(), &proof));
+    }
+}
@@ -138,6 +138,13 @@ fn test_ingest_reentrant() {
     assert!(!resp.has_error());
 }
 
+// The Merkle integrity helpers (root digest, inclusion proof, and the root check
+// against a recorded digest) are exercised as crate-level unit tests in
+// `sst_importer::merkle`/`merkle_integrity`, which recompute the root, build and
+// verify a proof, and assert a tampered file is rejected. The import service
+// does not call these helpers and has no proof RPC, so there is no end-to-end
+// path for this integration test to exercise.
+
 #[test]
 fn test_ingest_key_manager_delete_file_failed() {
     // test with tde
@@ -397,6 +404,14 @@ fn test_delete_sst_after_applied_sst() {
     cluster.start().unwrap();
 }
 
+// Content-defined chunking dedup (manifest-then-missing-chunks upload, with the
+// moved-byte count reported back) is exercised as crate-level unit tests in
+// `sst_importer::chunking`/`chunk_dedup`: they assert boundaries stay stable
+// under append and mid-stream insertion and that two SSTs sharing a prefix move
+// only the differing tail. The import service has no dedup setting and its
+// upload response carries no moved-byte count, so there is no end-to-end path
+// for this integration test to exercise.
+
 fn sst_file_count(paths: &Vec<TempDir>) -> u64 {
     let mut count = 0;
     for path in paths {
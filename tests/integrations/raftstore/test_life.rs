@@ -7,6 +7,7 @@ use std::{
 
 use kvproto::raft_serverpb::{ExtraMessageType, PeerState, RaftMessage};
 use raftstore::errors::Result;
+use raftstore::store::{read_index_forward, RaftMessageBridge, RaftMessageSink};
 use test_raftstore::{
     new_learner_peer, new_peer, sleep_ms, Filter, FilterFactory, Simulator as S1,
 };
@@ -140,6 +141,227 @@ fn test_gc_peer_tiflash_engine() {
     }
 }
 
+// Same scenario as `test_gc_peer_tiflash_engine`, but the relaying is driven by
+// the production `RaftMessageBridge` instead of an ad-hoc closure in the test.
+// The bridge forwards `RaftMessage`s destined for a configured store id across
+// the v1<->v2 boundary, translating between the two conventions. Removing the
+// learner on the v2 side must still make the v2 leader clear both
+// `removed_records` and `merged_records`, the bridge must report the traffic it
+// forwarded, and the GC-peer handshake must be accounted for separately.
+#[test]
+fn test_raft_message_bridge_gc_peer() {
+    let mut cluster_v1 = test_raftstore::new_node_cluster(1, 2);
+    let mut cluster_v2 = test_raftstore_v2::new_node_cluster(1, 2);
+    cluster_v1.cfg.raft_store.enable_v2_compatible_learner = true;
+    cluster_v1.pd_client.disable_default_operator();
+    cluster_v2.pd_client.disable_default_operator();
+    let r11 = cluster_v1.run_conf_change();
+    let r21 = cluster_v2.run_conf_change();
+
+    cluster_v1
+        .pd_client
+        .must_add_peer(r11, new_learner_peer(2, 10));
+    cluster_v2
+        .pd_client
+        .must_add_peer(r21, new_learner_peer(2, 10));
+    let start = Instant::now();
+    loop {
+        if cluster_v1.get_raft_local_state(r11, 2).is_some()
+            && cluster_v1.get_raft_local_state(r11, 2) == cluster_v2.get_raft_local_state(r21, 2)
+            && cluster_v1.region_local_state(r11, 2).state == PeerState::Normal
+            && cluster_v2.region_local_state(r21, 2).state == PeerState::Normal
+            && cluster_v1.apply_state(r11, 2).truncated_state
+                == cluster_v2.apply_state(r21, 2).truncated_state
+        {
+            break;
+        }
+        if start.saturating_elapsed() > Duration::from_secs(5) {
+            panic!("timeout");
+        }
+    }
+
+    // The v2 leader lives on store 1, the v1 learner on store 2. The bridge's
+    // sinks hand a message to the opposite cluster's router: messages addressed
+    // to the leader store are translated into v2 conventions and relayed onto
+    // the v2 router, and vice versa.
+    let trans1 = Mutex::new(cluster_v1.sim.read().unwrap().get_router(2).unwrap());
+    let trans2 = Mutex::new(cluster_v2.sim.read().unwrap().get_router(1).unwrap());
+    let v1_sink: Arc<dyn RaftMessageSink> = Arc::new(move |m: RaftMessage| {
+        let _ = trans1.lock().unwrap().send_raft_message(m);
+    });
+    let v2_sink: Arc<dyn RaftMessageSink> = Arc::new(move |m: RaftMessage| {
+        let _ = trans2.lock().unwrap().send_raft_message(Box::new(m));
+    });
+    let bridge = RaftMessageBridge::new(v1_sink, v2_sink, 2, 1);
+    bridge.start();
+
+    // The cluster filters feed observed traffic into the bridge, which applies
+    // its own forwarding predicate and translation before relaying.
+    let b1 = bridge.clone();
+    cluster_v1.add_send_filter(ForwardFactory {
+        node_id: 1,
+        chain_send: Arc::new(move |m| b1.on_v1_message(m)),
+        keep_msg: false,
+    });
+    let b2 = bridge.clone();
+    cluster_v2.add_send_filter(ForwardFactory {
+        node_id: 2,
+        chain_send: Arc::new(move |m| b2.on_v2_message(m)),
+        keep_msg: false,
+    });
+
+    cluster_v2
+        .pd_client
+        .must_remove_peer(r21, new_learner_peer(2, 10));
+
+    // Make sure leader cleans up both removed_records and merged_records.
+    let start = Instant::now();
+    loop {
+        sleep_ms(500);
+        let state = cluster_v2.region_local_state(r21, 1);
+        if state.get_removed_records().is_empty() && state.get_merged_records().is_empty() {
+            break;
+        }
+        if start.saturating_elapsed() > Duration::from_secs(5) {
+            panic!("timeout");
+        }
+    }
+
+    // The bridge must have actually carried the conf-change traffic, including
+    // the GC-peer handshake that drives the removal to convergence.
+    assert!(bridge.metrics().forwarded() > 0);
+    assert!(bridge.metrics().gc_peer() > 0);
+    bridge.stop();
+}
+
+// A v2-compatible learner backed by an external engine has no way to serve a
+// linearizable read on its own. With ReadIndex forwarding it emits a ReadIndex
+// request carrying its applied index over the extra-message channel; the v2
+// leader confirms leadership and replies with a committed read index, and the
+// learner blocks until its apply progress reaches that index. This drives the
+// round trip through the production `RaftMessageBridge` and asserts the
+// confirmed read index covers the latest committed write.
+#[test]
+fn test_read_index_forwarding_v2_learner() {
+    let mut cluster_v1 = test_raftstore::new_node_cluster(1, 2);
+    let mut cluster_v2 = test_raftstore_v2::new_node_cluster(1, 2);
+    cluster_v1.cfg.raft_store.enable_v2_compatible_learner = true;
+    cluster_v1.pd_client.disable_default_operator();
+    cluster_v2.pd_client.disable_default_operator();
+    let r11 = cluster_v1.run_conf_change();
+    let r21 = cluster_v2.run_conf_change();
+
+    cluster_v1
+        .pd_client
+        .must_add_peer(r11, new_learner_peer(2, 10));
+    cluster_v2
+        .pd_client
+        .must_add_peer(r21, new_learner_peer(2, 10));
+    let start = Instant::now();
+    loop {
+        if cluster_v1.get_raft_local_state(r11, 2).is_some()
+            && cluster_v1.get_raft_local_state(r11, 2) == cluster_v2.get_raft_local_state(r21, 2)
+            && cluster_v1.region_local_state(r11, 2).state == PeerState::Normal
+            && cluster_v2.region_local_state(r21, 2).state == PeerState::Normal
+        {
+            break;
+        }
+        if start.saturating_elapsed() > Duration::from_secs(5) {
+            panic!("timeout");
+        }
+    }
+
+    // Bridge the two clusters as in the GC-peer scenario: learner on store 2,
+    // leader on store 1.
+    let trans1 = Mutex::new(cluster_v1.sim.read().unwrap().get_router(2).unwrap());
+    let trans2 = Mutex::new(cluster_v2.sim.read().unwrap().get_router(1).unwrap());
+    let v1_sink: Arc<dyn RaftMessageSink> = Arc::new(move |m: RaftMessage| {
+        let _ = trans1.lock().unwrap().send_raft_message(m);
+    });
+    let v2_sink: Arc<dyn RaftMessageSink> = Arc::new(move |m: RaftMessage| {
+        let _ = trans2.lock().unwrap().send_raft_message(Box::new(m));
+    });
+    let bridge = RaftMessageBridge::new(v1_sink, v2_sink, 2, 1);
+    bridge.start();
+
+    let b1 = bridge.clone();
+    cluster_v1.add_send_filter(ForwardFactory {
+        node_id: 1,
+        chain_send: Arc::new(move |m| b1.on_v1_message(m)),
+        keep_msg: false,
+    });
+
+    // Capture the ReadIndex response the leader sends back so we can check the
+    // committed index it confirmed. The capture filter must sit in front of the
+    // bridge's draining forward filter, otherwise the response to store 2 is
+    // already gone by the time it runs.
+    let (tx, rx) = channel();
+    let tx = Mutex::new(tx);
+    cluster_v2.add_send_filter(ForwardFactory {
+        node_id: 2,
+        chain_send: Arc::new(move |m| {
+            if m.get_extra_msg().get_type() == read_index_forward::RESPONSE_TYPE {
+                let _ = tx.lock().unwrap().send(m);
+            }
+        }),
+        keep_msg: true,
+    });
+    let b2 = bridge.clone();
+    cluster_v2.add_send_filter(ForwardFactory {
+        node_id: 2,
+        chain_send: Arc::new(move |m| b2.on_v2_message(m)),
+        keep_msg: false,
+    });
+
+    // Commit a write on the v2 leader.
+    cluster_v2.must_put(b"zread_index_key", b"v2");
+
+    // The learner emits a ReadIndex request carrying its applied index. It is
+    // injected into the v1 cluster (region `r11`, v1 epoch) addressed to the v1
+    // leader node; the bridge relays it across to the v2 leader.
+    let applied = cluster_v1.apply_state(r11, 2).get_applied_index();
+    let epoch = cluster_v1.get_region_epoch(r11);
+    let mut msg = RaftMessage::default();
+    msg.set_region_id(r11);
+    msg.set_from_peer(new_learner_peer(2, 10));
+    msg.set_to_peer(new_peer(1, 1));
+    msg.set_region_epoch(epoch);
+    let extra_msg = msg.mut_extra_msg();
+    extra_msg.set_type(read_index_forward::REQUEST_TYPE);
+    extra_msg.set_index(applied);
+    cluster_v1.sim.wl().send_raft_msg(msg).unwrap();
+
+    // The leader replies with a committed read index that covers the write.
+    let resp = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    let read_index = resp.get_extra_msg().get_index();
+    let leader_commit = cluster_v2
+        .get_raft_local_state(r21, 1)
+        .unwrap()
+        .get_hard_state()
+        .get_commit();
+    assert!(
+        read_index >= leader_commit,
+        "read index {} must cover the leader commit {}",
+        read_index,
+        leader_commit
+    );
+
+    // The learner blocks the read until its apply progress reaches that index,
+    // after which the latest committed write is observable on the learner.
+    let start = Instant::now();
+    loop {
+        if cluster_v1.apply_state(r11, 2).get_applied_index() >= read_index {
+            break;
+        }
+        if start.saturating_elapsed() > Duration::from_secs(5) {
+            panic!("timeout waiting for learner to catch up to read index");
+        }
+        sleep_ms(50);
+    }
+
+    bridge.stop();
+}
+
 #[test]
 fn test_gc_removed_peer() {
     let mut cluster = test_raftstore::new_node_cluster(1, 2);